@@ -0,0 +1,35 @@
+use anyhow::{Context, Result};
+use bluer::adv::{Advertisement, Type};
+use bluer::Adapter;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+/// Broadcasts a BLE advertisement built from `local_name`/`service_uuids`/
+/// `manufacturer_data` from `adapter` as a peripheral, unregistering it again
+/// once the user interrupts with Ctrl+C.
+pub async fn run(
+    adapter: &Adapter,
+    local_name: Option<String>,
+    service_uuids: BTreeSet<Uuid>,
+    manufacturer_data: BTreeMap<u16, Vec<u8>>,
+) -> Result<()> {
+    let advertisement = Advertisement {
+        advertisement_type: Type::Peripheral,
+        service_uuids,
+        manufacturer_data,
+        local_name,
+        discoverable: Some(true),
+        ..Default::default()
+    };
+
+    let handle = adapter
+        .advertise(advertisement)
+        .await
+        .context("Failed to register advertisement")?;
+
+    println!("Advertising as a BLE beacon. Press Ctrl+C to stop.");
+    tokio::signal::ctrl_c().await?;
+
+    drop(handle);
+    Ok(())
+}
@@ -1,29 +1,39 @@
-use bluer::{Device, AddressType, Modalias};
+use bluer::{Device, AddressType, Modalias, DeviceProperty};
 use uuid::Uuid;
 use anyhow::Result;
+use serde::{Serialize, Serializer};
 use std::collections::{HashSet, HashMap};
 
+#[derive(Serialize)]
 pub struct BluetoothData {
-    name: Option<String>,
-    address_type: AddressType,
-    icon: Option<String>,
-    appearance: Option<u16>,
-    uuids: Option<HashSet<Uuid>>,
-    paired: bool,
-    connected: bool,
-    trusted: bool,
-    blocked: bool,
-    wake_allowed: bool,
-    alias: String,
-    legacy_pairing: bool,
-    modalias: Option<Modalias>,
-    rssi: Option<i16>,
-    tx_power: Option<i16>,
-    manufacturer_data: Option<HashMap<u16, Vec<u8>>>,
-    service_data: Option<HashMap<Uuid, Vec<u8>>>,
-    services_resolved: bool,
-    advertising_flags: Vec<u8>,
-    advertising_data: HashMap<u8, Vec<u8>>
+    pub(crate) name: Option<String>,
+    #[serde(serialize_with = "serialize_address_type")]
+    pub(crate) address_type: AddressType,
+    pub(crate) icon: Option<String>,
+    pub(crate) class: Option<u32>,
+    pub(crate) appearance: Option<u16>,
+    #[serde(serialize_with = "serialize_uuids")]
+    pub(crate) uuids: Option<HashSet<Uuid>>,
+    pub(crate) paired: bool,
+    pub(crate) connected: bool,
+    pub(crate) trusted: bool,
+    pub(crate) blocked: bool,
+    pub(crate) wake_allowed: bool,
+    pub(crate) alias: String,
+    pub(crate) legacy_pairing: bool,
+    #[serde(serialize_with = "serialize_modalias")]
+    pub(crate) modalias: Option<Modalias>,
+    pub(crate) rssi: Option<i16>,
+    pub(crate) tx_power: Option<i16>,
+    #[serde(serialize_with = "serialize_hex_map_opt")]
+    pub(crate) manufacturer_data: Option<HashMap<u16, Vec<u8>>>,
+    #[serde(serialize_with = "serialize_hex_map_opt")]
+    pub(crate) service_data: Option<HashMap<Uuid, Vec<u8>>>,
+    pub(crate) services_resolved: bool,
+    #[serde(serialize_with = "serialize_hex_bytes")]
+    pub(crate) advertising_flags: Vec<u8>,
+    #[serde(serialize_with = "serialize_hex_map")]
+    pub(crate) advertising_data: HashMap<u8, Vec<u8>>
 }
 
 pub async fn device_to_data(device: &Device) -> Result<BluetoothData> {
@@ -31,6 +41,7 @@ pub async fn device_to_data(device: &Device) -> Result<BluetoothData> {
         name: device.name().await?,
         address_type: device.address_type().await?,
         icon: device.icon().await?,
+        class: device.class().await?,
         appearance: device.appearance().await?,
         uuids: device.uuids().await?,
         paired: device.is_paired().await?,
@@ -49,4 +60,110 @@ pub async fn device_to_data(device: &Device) -> Result<BluetoothData> {
         advertising_flags: device.advertising_flags().await?,
         advertising_data: device.advertising_data().await?
     })
-}
\ No newline at end of file
+}
+
+impl BluetoothData {
+    /// Patches the single field a `DeviceProperty` change refers to, rather than
+    /// re-fetching every property over D-Bus.
+    pub fn apply_change(&mut self, property: DeviceProperty) {
+        match property {
+            DeviceProperty::Name(name) => self.name = Some(name),
+            DeviceProperty::AddressType(address_type) => self.address_type = address_type,
+            DeviceProperty::Icon(icon) => self.icon = Some(icon),
+            DeviceProperty::Class(class) => self.class = Some(class),
+            DeviceProperty::Appearance(appearance) => self.appearance = Some(appearance),
+            DeviceProperty::Uuids(uuids) => self.uuids = Some(uuids),
+            DeviceProperty::Paired(paired) => self.paired = paired,
+            DeviceProperty::Connected(connected) => self.connected = connected,
+            DeviceProperty::Trusted(trusted) => self.trusted = trusted,
+            DeviceProperty::Blocked(blocked) => self.blocked = blocked,
+            DeviceProperty::WakeAllowed(wake_allowed) => self.wake_allowed = wake_allowed,
+            DeviceProperty::Alias(alias) => self.alias = alias,
+            DeviceProperty::LegacyPairing(legacy_pairing) => self.legacy_pairing = legacy_pairing,
+            DeviceProperty::Modalias(modalias) => self.modalias = Some(modalias),
+            DeviceProperty::Rssi(rssi) => self.rssi = Some(rssi),
+            DeviceProperty::TxPower(tx_power) => self.tx_power = Some(tx_power),
+            DeviceProperty::ManufacturerData(manufacturer_data) => {
+                self.manufacturer_data = Some(manufacturer_data)
+            }
+            DeviceProperty::ServiceData(service_data) => self.service_data = Some(service_data),
+            DeviceProperty::ServicesResolved(services_resolved) => {
+                self.services_resolved = services_resolved
+            }
+            DeviceProperty::AdvertisingFlags(advertising_flags) => {
+                self.advertising_flags = advertising_flags
+            }
+            DeviceProperty::AdvertisingData(advertising_data) => {
+                self.advertising_data = advertising_data
+            }
+            _ => {}
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn serialize_address_type<S>(address_type: &AddressType, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("{address_type:?}"))
+}
+
+fn serialize_modalias<S>(modalias: &Option<Modalias>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match modalias {
+        Some(modalias) => serializer.serialize_str(&format!("{modalias:?}")),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_uuids<S>(uuids: &Option<HashSet<Uuid>>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match uuids {
+        Some(uuids) => serializer.serialize_str(&itertools::join(uuids, ", ")),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Renders a `{key: hex-encoded-bytes}` map as a single comma-joined string, so that
+/// manufacturer/service/advertising data stay flat scalar fields in JSON and CSV
+/// output alike instead of nested structures.
+fn serialize_hex_map<K, S>(map: &HashMap<K, Vec<u8>>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    K: ToString,
+    S: Serializer,
+{
+    let joined = itertools::join(
+        map.iter().map(|(key, value)| format!("{}: {}", key.to_string(), to_hex(value))),
+        ", ",
+    );
+    serializer.serialize_str(&joined)
+}
+
+fn serialize_hex_map_opt<K, S>(
+    map: &Option<HashMap<K, Vec<u8>>>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    K: ToString,
+    S: Serializer,
+{
+    match map {
+        Some(map) => serialize_hex_map(map, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_hex_bytes<S>(bytes: &[u8], serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&to_hex(bytes))
+}
@@ -0,0 +1,67 @@
+use crate::device_processor::BluetoothData;
+use anyhow::{Context, Result};
+use bluer::Address;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// Output format for a completed scan, selected with `--format`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+const COLUMNS: &[&str] = &[
+    "address",
+    "name",
+    "address_type",
+    "icon",
+    "class",
+    "appearance",
+    "uuids",
+    "paired",
+    "connected",
+    "trusted",
+    "blocked",
+    "wake_allowed",
+    "alias",
+    "legacy_pairing",
+    "modalias",
+    "rssi",
+    "tx_power",
+    "manufacturer_data",
+    "service_data",
+    "services_resolved",
+    "advertising_flags",
+    "advertising_data",
+];
+
+/// Writes every cached device in `devices` to `path`, in the requested `format`.
+pub fn write(devices: &HashMap<Address, BluetoothData>, format: Format, path: &Path) -> Result<()> {
+    match format {
+        Format::Json => {
+            let file = File::create(path)
+                .with_context(|| format!("Could not create output file {}", path.display()))?;
+            let by_address: HashMap<String, &BluetoothData> = devices
+                .iter()
+                .map(|(address, data)| (address.to_string(), data))
+                .collect();
+            serde_json::to_writer_pretty(file, &by_address)?;
+        }
+        Format::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_path(path)
+                .with_context(|| format!("Could not create output file {}", path.display()))?;
+            writer.write_record(COLUMNS)?;
+            for (address, data) in devices {
+                writer.serialize((address.to_string(), data))?;
+            }
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
@@ -2,18 +2,92 @@ extern crate itertools;
 #[macro_use]
 extern crate prettytable;
 
+mod advertise;
 mod device_processor;
-
-use anyhow::{Context, Error, Result};
-use bluer::{Adapter, AdapterEvent, Address, Device, DeviceEvent, DeviceProperty, AddressType, Modalias};
+mod export;
+mod pairing;
+
+use anyhow::{bail, Context, Error, Result};
+use bluer::{
+    Adapter, AdapterEvent, Address, Device, DeviceEvent, DeviceProperty, DiscoveryFilter,
+    AddressType, Modalias,
+};
+use clap::{Parser, Subcommand};
+use device_processor::BluetoothData;
 use futures::{pin_mut, stream::SelectAll, StreamExt};
+use pairing::IoCapability;
 use prettytable::{format, Row};
 use single::Single;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::{collections::HashSet, env};
+use std::time::Duration;
 use terminal_emoji::Emoji;
 use tokio::sync::RwLock;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(name = "bluebooth", about = "Scan, inspect, and pair with nearby Bluetooth devices")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Scan for nearby devices and print a live table (default)
+    Scan {
+        /// Only track these addresses; if none are given, track every device seen
+        addresses: Vec<Address>,
+        /// Only surface devices advertising this service UUID (repeatable); if none
+        /// are given, every device is surfaced
+        #[arg(long = "service")]
+        services: Vec<Uuid>,
+        /// Stop discovery after this many seconds instead of scanning forever
+        #[arg(long)]
+        scan_time: Option<u64>,
+        /// Dump the collected devices to this file once the scan stops
+        #[arg(long, requires = "scan_time")]
+        output: Option<PathBuf>,
+        /// Format to use when writing `--output`
+        #[arg(long, value_enum, default_value_t = export::Format::Json)]
+        format: export::Format,
+    },
+    /// Pair, trust, and bond with a device
+    Pair {
+        /// The address of the device to pair with
+        address: Address,
+        /// The input/output capability to advertise to the pairing agent
+        #[arg(long, value_enum, default_value_t = IoCapability::DisplayYesNo)]
+        io_capability: IoCapability,
+    },
+    /// Broadcast as a BLE peripheral/beacon until interrupted
+    Advertise {
+        /// Local name to advertise
+        #[arg(long)]
+        name: Option<String>,
+        /// Service UUID to advertise (repeatable)
+        #[arg(long = "service")]
+        services: Vec<Uuid>,
+        /// Manufacturer ID for the manufacturer data entry (requires --manufacturer-data)
+        #[arg(long, requires = "manufacturer_data")]
+        manufacturer_id: Option<u16>,
+        /// Manufacturer data as a hex string (requires --manufacturer-id)
+        #[arg(long, requires = "manufacturer_id")]
+        manufacturer_data: Option<String>,
+    },
+}
+
+fn parse_hex_bytes(input: &str) -> Result<Vec<u8>> {
+    if input.len() % 2 != 0 {
+        bail!("Manufacturer data must have an even number of hex digits");
+    }
+
+    (0..input.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&input[i..i + 2], 16).map_err(Error::from))
+        .collect()
+}
 
 fn bool_to_emoji<'a>(flag: bool) -> Emoji<'a> {
     if flag {
@@ -29,10 +103,10 @@ fn get_device(adapter: &Adapter, addr: Address) -> bluer::Result<Device> {
     adapter.device(addr)
 }
 
-async fn to_bluetooth_info(device: &Device) -> Result<Row> {
-    let service_data = device.service_data().await?;
-
-    let service_data_string = service_data
+fn bluetooth_data_to_row(address: Address, data: &BluetoothData) -> Result<Row> {
+    let service_data_string = data
+        .service_data
+        .as_ref()
         .map(|data| {
             if data.len() == 1 {
                 data.values()
@@ -51,19 +125,19 @@ async fn to_bluetooth_info(device: &Device) -> Result<Row> {
         .unwrap_or_else(|| Ok("".to_string()))?;
 
     Ok(row![
-        Fb->device.address().to_string(),
-        Fy->device.name().await?.unwrap_or_else(|| "".to_string()),
-        Fb->device.icon().await?.unwrap_or_else(|| "".to_string()),
-        Fy->device.class().await?.map(|it| it.to_string()).unwrap_or_else(|| "".to_string()),
-        Fb->device.uuids().await?.map(|it| itertools::join(&it, ", ")).unwrap_or_else(|| "".to_string()),
-        Fyc->bool_to_emoji(device.is_paired().await?),
-        Fbc->bool_to_emoji(device.is_connected().await?),
-        Fyc->bool_to_emoji(device.is_trusted().await?),
-        Fb->device.modalias().await?.map(|it| format!("{:?}", it)).unwrap_or_else(|| "".to_string()),
-        Fy->device.rssi().await?.map(|it| it.to_string()).unwrap_or_else(|| "".to_string()),
-        Fb->device.tx_power().await?.map(|it| it.to_string()).unwrap_or_else(|| "".to_string()),
+        Fb->address.to_string(),
+        Fy->data.name.clone().unwrap_or_else(|| "".to_string()),
+        Fb->data.icon.clone().unwrap_or_else(|| "".to_string()),
+        Fy->data.class.map(|it| it.to_string()).unwrap_or_else(|| "".to_string()),
+        Fb->data.uuids.as_ref().map(|it| itertools::join(it, ", ")).unwrap_or_else(|| "".to_string()),
+        Fyc->bool_to_emoji(data.paired),
+        Fbc->bool_to_emoji(data.connected),
+        Fyc->bool_to_emoji(data.trusted),
+        Fb->data.modalias.as_ref().map(|it| format!("{:?}", it)).unwrap_or_else(|| "".to_string()),
+        Fy->data.rssi.map(|it| it.to_string()).unwrap_or_else(|| "".to_string()),
+        Fb->data.tx_power.map(|it| it.to_string()).unwrap_or_else(|| "".to_string()),
         Fy->service_data_string,
-        Fb->device.manufacturer_data().await?
+        Fb->data.manufacturer_data.as_ref()
             .map(|it| it.iter().map(|(k, v)| format!("{}: {}", k, itertools::join(v, ", "))).collect())
             .map_or_else(|| "".to_string(), |it: HashSet<String>| itertools::join(&it, ", ")),
     ])
@@ -71,8 +145,8 @@ async fn to_bluetooth_info(device: &Device) -> Result<Row> {
 
 type ThreadSafeBlueboothDeviceMap = Arc<
     RwLock<
-        // The devices address (for easy lookup) to the Row containing its data,
-        HashMap<Address, Device>,
+        // The devices address (for easy lookup) to the cached data fetched for it,
+        HashMap<Address, BluetoothData>,
     >,
 >;
 
@@ -98,8 +172,8 @@ async fn print_table(devices: ThreadSafeBlueboothDeviceMap) -> Result<()> {
     let format = *format::consts::FORMAT_BOX_CHARS;
     table.set_format(format);
 
-    for device in editable_devices.values() {
-        table.add_row(to_bluetooth_info(device).await?.clone());
+    for (address, data) in editable_devices.iter() {
+        table.add_row(bluetooth_data_to_row(*address, data)?);
     }
 
     table.printstd();
@@ -109,11 +183,13 @@ async fn print_table(devices: ThreadSafeBlueboothDeviceMap) -> Result<()> {
 
 async fn set_info(
     address: Address,
-    device: Device,
+    device: &Device,
     devices: ThreadSafeBlueboothDeviceMap,
-) -> std::io::Result<()> {
+) -> Result<()> {
+    let data = device_processor::device_to_data(device).await?;
+
     let mut writable_devices = devices.write().await;
-    writable_devices.insert(address, device);
+    writable_devices.insert(address, data);
 
     Ok(())
 }
@@ -133,53 +209,23 @@ async fn change_info(
     devices: ThreadSafeBlueboothDeviceMap,
     property: DeviceProperty,
 ) -> Result<()> {
-    let readable_devices = devices.read().await;
-
-    let device = match readable_devices.get(&address) {
-        None => return Ok(()),
-        Some(x) => x,
-    };
-
-    let devices = devices.clone();
     let mut writable_devices = devices.write().await;
 
-    match property {
-        DeviceProperty::Name(name) => device.set_alias(name).await?,
-        DeviceProperty::AddressType(address_type) => todo!(),
-        DeviceProperty::Icon(icon) => todo!(),
-        DeviceProperty::Class(class) => todo!(),
-        DeviceProperty::Appearance(appearance) => todo!(),
-        DeviceProperty::Uuids(uuids) => todo!(),
-        DeviceProperty::Paired(paired) => todo!(),
-        DeviceProperty::Connected(connected) => todo!(),
-        DeviceProperty::Trusted(trusted) => todo!(),
-        DeviceProperty::Blocked(blocked) => todo!(),
-        DeviceProperty::WakeAllowed(wake_allowed) => todo!(),
-        DeviceProperty::Alias(alias) => device.set_alias(alias).await?,
-        DeviceProperty::LegacyPairing(legacy_pairing) => todo!(),
-        DeviceProperty::Modalias(modalias) => todo!(),
-        DeviceProperty::Rssi(rssi) => todo!(),
-        DeviceProperty::TxPower(tx_power) => todo!(),
-        DeviceProperty::ManufacturerData(manufacturer_data) => todo!(),
-        DeviceProperty::ServiceData(service_data) => todo!(),
-        DeviceProperty::ServicesResolved(services_resolved) => todo!(),
-        DeviceProperty::AdvertisingFlags(advertising_flags) => todo!(),
-        DeviceProperty::AdvertisingData(advertising_data) => todo!(),
-    };
-
-    writable_devices.insert(address, device.clone());
+    if let Some(data) = writable_devices.get_mut(&address) {
+        data.apply_change(property);
+    }
 
     Ok(())
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
+async fn run_scan(
+    filter_addr: HashSet<Address>,
+    filter_services: HashSet<Uuid>,
+    scan_time: Option<u64>,
+    output: Option<(PathBuf, export::Format)>,
+) -> Result<()> {
     let devices: ThreadSafeBlueboothDeviceMap = Arc::new(RwLock::new(HashMap::new()));
 
-    let filter_addr: HashSet<_> = env::args()
-        .filter_map(|arg| arg.parse::<Address>().ok())
-        .collect();
-
     let session = bluer::Session::new().await?;
     let adapter_names = session.adapter_names().await?;
     let adapter_name = adapter_names
@@ -192,13 +238,30 @@ async fn main() -> Result<()> {
     let adapter = session.adapter(adapter_name)?;
     adapter.set_powered(true).await?;
 
+    if !filter_services.is_empty() {
+        adapter
+            .set_discovery_filter(DiscoveryFilter {
+                uuids: filter_services.clone(),
+                ..Default::default()
+            })
+            .await?;
+    }
+
     let device_events = adapter.discover_devices().await?;
     pin_mut!(device_events);
 
     let mut all_change_events = SelectAll::new();
 
+    // `Duration::MAX` stands in for "no deadline" so the same branch handles both
+    // the bounded and unbounded scan without duplicating the select loop.
+    let deadline = tokio::time::sleep(scan_time.map(Duration::from_secs).unwrap_or(Duration::MAX));
+    pin_mut!(deadline);
+
     loop {
         tokio::select! {
+            () = &mut deadline => {
+                break;
+            }
             Some(device_event) = device_events.next() => {
                 match device_event {
                     AdapterEvent::DeviceAdded(addr) => {
@@ -208,10 +271,25 @@ async fn main() -> Result<()> {
 
                         let device = get_device(&adapter, addr)?;
 
-                        set_info(addr, device, devices.clone()).await?;
+                        set_info(addr, &device, devices.clone()).await?;
+
+                        if !filter_services.is_empty() {
+                            let advertises_filtered_service = devices
+                                .read()
+                                .await
+                                .get(&addr)
+                                .and_then(|data| data.uuids.as_ref())
+                                .map(|uuids| uuids.intersection(&filter_services).next().is_some())
+                                .unwrap_or(false);
+
+                            if !advertises_filtered_service {
+                                remove_info(addr, devices.clone()).await?;
+                                continue;
+                            }
+                        }
+
                         print_table(devices.clone()).await?;
 
-                        let device = adapter.device(addr)?;
                         let change_events = device.events().await?.map(move |evt| (addr, evt));
                         all_change_events.push(change_events);
                     }
@@ -224,11 +302,96 @@ async fn main() -> Result<()> {
                 println!();
             }
             Some((addr, DeviceEvent::PropertyChanged(property))) = all_change_events.next() => {
-                change_info(addr, devices.clone(), property).await;
+                change_info(addr, devices.clone(), property).await?;
             }
             else => break
         }
     }
 
+    if let Some((path, format)) = output {
+        let readable_devices = devices.read().await;
+        export::write(&readable_devices, format, &path)
+            .with_context(|| format!("Could not write scan results to {}", path.display()))?;
+        println!("Wrote {} device(s) to {}", readable_devices.len(), path.display());
+    }
+
     Ok(())
 }
+
+async fn run_pair(address: Address, io_capability: IoCapability) -> Result<()> {
+    let session = bluer::Session::new().await?;
+    let adapter_names = session.adapter_names().await?;
+    let adapter_name = adapter_names
+        .first()
+        .context("No Bluetooth adapter present")?;
+    let adapter = session.adapter(adapter_name)?;
+    adapter.set_powered(true).await?;
+
+    let _agent_handle = pairing::register_agent(&session, io_capability).await?;
+
+    println!("Pairing with {address}...");
+    pairing::pair_device(&adapter, address).await?;
+
+    let devices: ThreadSafeBlueboothDeviceMap = Arc::new(RwLock::new(HashMap::new()));
+    let device = get_device(&adapter, address)?;
+    set_info(address, &device, devices.clone()).await?;
+    print_table(devices).await?;
+
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let default_command = Command::Scan {
+        addresses: Vec::new(),
+        services: Vec::new(),
+        scan_time: None,
+        output: None,
+        format: export::Format::Json,
+    };
+
+    match cli.command.unwrap_or(default_command) {
+        Command::Scan {
+            addresses,
+            services,
+            scan_time,
+            output,
+            format,
+        } => {
+            run_scan(
+                addresses.into_iter().collect(),
+                services.into_iter().collect(),
+                scan_time,
+                output.map(|path| (path, format)),
+            )
+            .await
+        }
+        Command::Pair {
+            address,
+            io_capability,
+        } => run_pair(address, io_capability).await,
+        Command::Advertise {
+            name,
+            services,
+            manufacturer_id,
+            manufacturer_data,
+        } => {
+            let manufacturer_data = match (manufacturer_id, manufacturer_data) {
+                (Some(id), Some(hex)) => BTreeMap::from([(id, parse_hex_bytes(&hex)?)]),
+                _ => BTreeMap::new(),
+            };
+
+            let session = bluer::Session::new().await?;
+            let adapter_names = session.adapter_names().await?;
+            let adapter_name = adapter_names
+                .first()
+                .context("No Bluetooth adapter present")?;
+            let adapter = session.adapter(adapter_name)?;
+            adapter.set_powered(true).await?;
+
+            advertise::run(&adapter, name, services.into_iter().collect(), manufacturer_data).await
+        }
+    }
+}
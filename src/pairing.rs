@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use bluer::agent::{
+    Agent, AgentHandle, DisplayPasskey, DisplayPinCode, ReqError, RequestAuthorization,
+    RequestConfirmation, RequestPasskey, RequestPinCode,
+};
+use bluer::{Adapter, Address, Session};
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+/// The input/output capability advertised to the pairing agent. It determines
+/// which of the prompts below BlueZ will route to us while pairing.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum IoCapability {
+    /// Can only display a passkey or PIN, no input.
+    DisplayOnly,
+    /// Can display a passkey and ask the user to confirm it.
+    DisplayYesNo,
+    /// Can only accept a typed passkey or PIN, no display.
+    KeyboardOnly,
+    /// No PIN/passkey display, entry or confirmation; only the authorization prompt.
+    NoInputNoOutput,
+    /// Can both display and accept a passkey or PIN.
+    KeyboardDisplay,
+}
+
+fn prompt(message: &str) -> io::Result<String> {
+    print!("{message}: ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+fn confirm(message: &str) -> bool {
+    prompt(&format!("{message} [y/N]"))
+        .map(|answer| answer.eq_ignore_ascii_case("y"))
+        .unwrap_or(false)
+}
+
+/// Registers a pairing agent on `session` configured for `io_capability`, prompting
+/// on the terminal for whichever of the PIN/passkey/confirmation callbacks that
+/// capability implies; the authorization prompt is registered for every capability.
+/// The returned handle must be kept alive for as
+/// long as the agent should stay registered.
+pub async fn register_agent(session: &Session, io_capability: IoCapability) -> Result<AgentHandle> {
+    let can_display = matches!(
+        io_capability,
+        IoCapability::DisplayOnly | IoCapability::DisplayYesNo | IoCapability::KeyboardDisplay
+    );
+    let can_input = matches!(
+        io_capability,
+        IoCapability::KeyboardOnly | IoCapability::KeyboardDisplay
+    );
+    let can_confirm = matches!(io_capability, IoCapability::DisplayYesNo);
+
+    let mut agent = Agent {
+        request_default: matches!(io_capability, IoCapability::NoInputNoOutput),
+        ..Default::default()
+    };
+
+    if can_display {
+        agent.display_passkey = Some(Box::new(|req: DisplayPasskey| {
+            Box::pin(async move {
+                println!(
+                    "Passkey for {}: {:06} (entered {} digits)",
+                    req.device,
+                    req.passkey,
+                    req.entered
+                );
+                Ok(())
+            })
+        }));
+        agent.display_pin_code = Some(Box::new(|req: DisplayPinCode| {
+            Box::pin(async move {
+                println!("PIN code for {}: {}", req.device, req.pincode);
+                Ok(())
+            })
+        }));
+    }
+
+    if can_input {
+        agent.request_passkey = Some(Box::new(|req: RequestPasskey| {
+            Box::pin(async move {
+                prompt(&format!("Enter passkey for {}", req.device))
+                    .ok()
+                    .and_then(|answer| answer.parse().ok())
+                    .ok_or(ReqError::Canceled)
+            })
+        }));
+        agent.request_pin_code = Some(Box::new(|req: RequestPinCode| {
+            Box::pin(async move {
+                prompt(&format!("Enter PIN code for {}", req.device))
+                    .map_err(|_| ReqError::Canceled)
+            })
+        }));
+    }
+
+    if can_confirm {
+        agent.request_confirmation = Some(Box::new(|req: RequestConfirmation| {
+            Box::pin(async move {
+                let message = format!("Confirm passkey {:06} for {}", req.passkey, req.device);
+                if confirm(&message) {
+                    Ok(())
+                } else {
+                    Err(ReqError::Rejected)
+                }
+            })
+        }));
+    }
+
+    agent.request_authorization = Some(Box::new(|req: RequestAuthorization| {
+        Box::pin(async move {
+            if confirm(&format!("Authorize pairing with {}", req.device)) {
+                Ok(())
+            } else {
+                Err(ReqError::Rejected)
+            }
+        })
+    }));
+
+    session
+        .register_agent(agent)
+        .await
+        .context("Failed to register pairing agent")
+}
+
+/// Pairs with, trusts, and un-blocks the device at `address` on `adapter`. Any
+/// PIN/passkey/confirmation/authorization requests BlueZ sends during pairing are
+/// fielded by whichever agent is currently registered on the session.
+pub async fn pair_device(adapter: &Adapter, address: Address) -> Result<()> {
+    let device = adapter.device(address)?;
+
+    if !device.is_paired().await? {
+        device.pair().await.context("Pairing failed")?;
+    }
+
+    device.set_trusted(true).await?;
+    device.set_blocked(false).await?;
+
+    Ok(())
+}